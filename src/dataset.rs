@@ -1,21 +1,227 @@
 use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::str::FromStr;
+use std::rc::Rc;
 use std::io::{BufRead, BufReader};
-use std::path::{Iter, Path, PathBuf};
+use std::path::{Path, PathBuf};
+use unicode_normalization::UnicodeNormalization;
+use flate2::read::MultiGzDecoder;
 
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+/// Vocabulary plus, when one was resolved, an aligned pretrained embedding
+/// matrix. Returned by [`ClassifierDataset::resolve_vocab`].
+type ResolvedVocab = Result<(BTreeMap<String, usize>, Option<Vec<Vec<f32>>>), DatasetError>;
+
+/// Vocabulary plus its aligned pretrained embedding matrix, both assigned in
+/// file order. Returned by [`ClassifierDataset::load_embedding`].
+type LoadedEmbedding = Result<(BTreeMap<String, usize>, Vec<Vec<f32>>), DatasetError>;
+
+/// Error returned by any fallible operation in this module: a missing or
+/// unreadable file, a malformed line, or a vocabulary that ended up empty.
+/// `#[non_exhaustive]` so new failure modes can be added without breaking
+/// callers that match on it.
+#[non_exhaustive]
+#[derive(Debug)]
+pub enum DatasetError{
+    Io(std::io::Error),
+    MissingField{ line: usize, path: PathBuf },
+    BadLabel{ value: String, line: usize },
+    EmptyVocab,
+    Parse{ line: usize, path: PathBuf, message: String }
+}
+
+impl std::fmt::Display for DatasetError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self{
+            DatasetError::Io(e) => write!(f, "io error: {}", e),
+            DatasetError::MissingField{ line, path } =>
+                write!(f, "{}:{}: missing field(s)", path.display(), line),
+            DatasetError::BadLabel{ value, line } =>
+                write!(f, "line {}: invalid label {:?}", line, value),
+            DatasetError::EmptyVocab => write!(f, "vocabulary is empty"),
+            DatasetError::Parse{ line, path, message } =>
+                write!(f, "{}:{}: {}", path.display(), line, message)
+        }
+    }
+}
+
+impl std::error::Error for DatasetError{
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self{
+            DatasetError::Io(e) => Some(e),
+            _ => None
+        }
+    }
+}
+
+impl From<std::io::Error> for DatasetError{
+    fn from(e: std::io::Error) -> Self {
+        DatasetError::Io(e)
+    }
+}
+
+/// Maps a [`ParseError`] produced while reading `path` at `line` onto the
+/// richer, file/line-aware [`DatasetError`].
+fn parse_error_to_dataset(err: ParseError, line: usize, path: &Path) -> DatasetError{
+    match err{
+        ParseError::FieldCount{..} => DatasetError::MissingField{ line, path: path.to_path_buf() },
+        ParseError::InvalidField(message) => DatasetError::Parse{ line, path: path.to_path_buf(), message },
+        #[cfg(feature = "json-lines")]
+        ParseError::Json(message) => DatasetError::Parse{ line, path: path.to_path_buf(), message }
+    }
+}
+
+/// Opens `path` for reading, transparently wrapping it in a gzip decoder when
+/// its first two bytes match the gzip magic number. Detection is by content,
+/// not extension, so `train.txt.gz` and `train.txt` are both handled without
+/// any change to the calling code.
+fn open_reader(path: &Path) -> Result<Box<dyn BufRead>, DatasetError>{
+    let file = File::open(path)?;
+    let mut reader = BufReader::new(file);
+    let is_gzip = reader.fill_buf()?.starts_with(&GZIP_MAGIC);
+    if is_gzip {
+        Ok(Box::new(BufReader::new(MultiGzDecoder::new(reader))))
+    }else {
+        Ok(Box::new(reader))
+    }
+}
+
+#[allow(dead_code)]
 enum Vocabulary{
     Vocab(String),
     Embedding(String),
     Empty
 }
 
+/// Unicode normalization form applied to raw text before it is tokenized,
+/// so visually identical sequences (full-width vs half-width, composed vs
+/// decomposed accents, compatibility forms) collapse to the same vocab entry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NormForm{
+    Nfc,
+    Nfd,
+    Nfkc,
+    Nfkd
+}
+
+fn normalize_text(text: &str, form: Option<NormForm>) -> String{
+    match form {
+        None => text.to_string(),
+        Some(NormForm::Nfc) => text.nfc().collect(),
+        Some(NormForm::Nfd) => text.nfd().collect(),
+        Some(NormForm::Nfkc) => text.nfkc().collect(),
+        Some(NormForm::Nfkd) => text.nfkd().collect(),
+    }
+}
+
+/// How a raw line is split into fields before being handed to a
+/// [`RecordParser`]. `JsonLines` bypasses splitting entirely and is only
+/// available when the `json-lines` feature is enabled.
+#[derive(Debug, Clone, Copy)]
+pub enum Delimiter{
+    Tab,
+    Comma,
+    Whitespace,
+    Custom(char),
+    #[cfg(feature = "json-lines")]
+    JsonLines
+}
+
+impl Delimiter{
+    /// Splits `line` into at most `max_fields` pieces, so any occurrence of
+    /// the delimiter beyond the `max_fields - 1`th stays embedded in the
+    /// last field instead of shifting the field count (e.g. the classifier
+    /// row `a\tb\tc` splits into text `a` and label `b\tc`, not 3 fields).
+    /// `Whitespace` treats each run of whitespace as one delimiter.
+    fn split<'a>(&self, line: &'a str, max_fields: usize) -> Vec<&'a str>{
+        match self{
+            Delimiter::Tab => line.splitn(max_fields, '\t').collect(),
+            Delimiter::Comma => line.splitn(max_fields, ',').collect(),
+            Delimiter::Whitespace => {
+                let mut fields = Vec::new();
+                let mut rest = line.trim_start();
+                while !rest.is_empty(){
+                    if fields.len() + 1 == max_fields{
+                        fields.push(rest);
+                        break;
+                    }
+                    match rest.find(char::is_whitespace){
+                        Some(i) => {
+                            fields.push(&rest[..i]);
+                            rest = rest[i..].trim_start();
+                        }
+                        None => {
+                            fields.push(rest);
+                            break;
+                        }
+                    }
+                }
+                fields
+            }
+            Delimiter::Custom(c) => line.splitn(max_fields, *c).collect(),
+            #[cfg(feature = "json-lines")]
+            Delimiter::JsonLines => Vec::new()
+        }
+    }
+}
+
+/// Error returned by a [`RecordParser`] when a line doesn't match the
+/// configured format.
+#[derive(Debug)]
+pub enum ParseError{
+    FieldCount{ expected: usize, found: usize },
+    InvalidField(String),
+    #[cfg(feature = "json-lines")]
+    Json(String)
+}
+
+impl std::fmt::Display for ParseError{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self{
+            ParseError::FieldCount{ expected, found } =>
+                write!(f, "expected {} fields, found {}", expected, found),
+            ParseError::InvalidField(msg) => write!(f, "invalid field: {}", msg),
+            #[cfg(feature = "json-lines")]
+            ParseError::Json(msg) => write!(f, "invalid json line: {}", msg)
+        }
+    }
+}
+
+impl std::error::Error for ParseError{}
+
+/// Implemented by every sample type so the dataset iterators can parse a
+/// line (or JSON object) without being locked to tab-delimited text.
+pub trait RecordParser: Sized{
+    fn fields_expected() -> usize;
+    fn parse_fields(fields: &[&str]) -> Result<Self, ParseError>;
+    #[cfg(feature = "json-lines")]
+    fn parse_json(_line: &str) -> Result<Self, ParseError>{
+        Err(ParseError::Json("json-lines format is not supported for this record type".to_string()))
+    }
+}
+
+/// Splits `line` per `delimiter` and dispatches to `T::parse_fields`, or to
+/// `T::parse_json` when `delimiter` is `Delimiter::JsonLines`.
+fn parse_line<T: RecordParser>(line: &str, delimiter: Delimiter) -> Result<T, ParseError>{
+    #[cfg(feature = "json-lines")]
+    if let Delimiter::JsonLines = delimiter{
+        return T::parse_json(line);
+    }
+    let fields = delimiter.split(line, T::fields_expected());
+    T::parse_fields(&fields)
+}
+
 #[allow(non_snake_case)]
 pub struct DataConfig{
     UNK: String,
     PAD: String,
     vocab_type: Vocabulary,
-    max_length: usize
+    #[allow(dead_code)]
+    max_length: usize,
+    normalization: Option<NormForm>,
+    min_freq: usize,
+    max_vocab_size: Option<usize>,
+    delimiter: Delimiter
 }
 
 impl DataConfig{
@@ -26,9 +232,33 @@ impl DataConfig{
             UNK: UNK_TOKEN,
             PAD: PAD_TOKEN,
             vocab_type: Vocabulary::Empty,
-            max_length
+            max_length,
+            normalization: None,
+            min_freq: 1,
+            max_vocab_size: None,
+            delimiter: Delimiter::Tab
         }
     }
+
+    pub fn with_normalization(mut self, form: NormForm) -> Self{
+        self.normalization = Some(form);
+        self
+    }
+
+    pub fn with_min_freq(mut self, min_freq: usize) -> Self{
+        self.min_freq = min_freq;
+        self
+    }
+
+    pub fn with_max_vocab_size(mut self, max_vocab_size: usize) -> Self{
+        self.max_vocab_size = Some(max_vocab_size);
+        self
+    }
+
+    pub fn with_delimiter(mut self, delimiter: Delimiter) -> Self{
+        self.delimiter = delimiter;
+        self
+    }
 }
 
 impl Default for DataConfig{
@@ -37,12 +267,28 @@ impl Default for DataConfig{
             UNK: String::from("<UNK>"),
             PAD: String::from("<PAD>"),
             vocab_type: Vocabulary::Empty,
-            max_length: 32
+            max_length: 32,
+            normalization: None,
+            min_freq: 1,
+            max_vocab_size: None,
+            delimiter: Delimiter::Tab
         }
     }
 }
+/// Result of [`ClassifierDataset::build_dataset`]: the train/dev/test
+/// records, the resolved vocabulary, and the pretrained embedding matrix
+/// (aligned to vocab ids) when `config.vocab_type` is `Embedding`.
+pub struct BuildDataset<const N: usize>{
+    pub train: Vec<ClassifierRecord<N>>,
+    pub dev: Vec<ClassifierRecord<N>>,
+    pub test: Vec<ClassifierRecord<N>>,
+    pub vocab: BTreeMap<String, usize>,
+    pub embeddings: Option<Vec<Vec<f32>>>
+}
+
 // classifier
 pub struct ClassifierDataset<'a>{
+    #[allow(dead_code)]
     path: & 'a Path,
     train_file: PathBuf,
     dev_file: PathBuf,
@@ -83,124 +329,337 @@ impl<'a> ClassifierDataset<'a>{
             config
         }
     }
-    fn train_iter(&self) -> ClassifierIter{
-        let file = File::open(self.train_file.as_path()).expect("open train file failed");
-        ClassifierIter::new(BufReader::new(file))
+    fn train_iter(&self) -> Result<ClassifierIter, DatasetError>{
+        Ok(ClassifierIter::new(open_reader(self.train_file.as_path())?, self.train_file.clone(), self.config.normalization, self.config.delimiter))
     }
-    fn dev_iter(&self) -> ClassifierIter{
-        let file = File::open(self.dev_file.as_path()).expect("open dev file failed");
-        ClassifierIter::new(BufReader::new(file))
+    fn dev_iter(&self) -> Result<ClassifierIter, DatasetError>{
+        Ok(ClassifierIter::new(open_reader(self.dev_file.as_path())?, self.dev_file.clone(), self.config.normalization, self.config.delimiter))
     }
-    fn test_iter(&self) -> ClassifierIter{
-        let file = File::open(self.test_file.as_path()).expect("open dev file failed");
-        ClassifierIter::new(BufReader::new(file))
+    fn test_iter(&self) -> Result<ClassifierIter, DatasetError>{
+        Ok(ClassifierIter::new(open_reader(self.test_file.as_path())?, self.test_file.clone(), self.config.normalization, self.config.delimiter))
     }
-    fn read_classes(&self) ->Vec<String>{
-        let file = File::open(self.class_file.as_path()).expect("open class file failed");
+    fn read_classes(&self) -> Result<Vec<String>, DatasetError>{
+        let file = File::open(self.class_file.as_path())?;
         BufReader::new(file)
             .lines()
-            .filter_map(|line|line.ok())
-            .collect()
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(DatasetError::from)
     }
-    pub fn build_dataset<const N: usize>(&self) ->(
-        Vec<ClassifierRecord<N>>,
-        Vec<ClassifierRecord<N>>,
-        Vec<ClassifierRecord<N>>,
-        BTreeMap<String, usize>
-    ){
-        let class2id = self.read_classes()
+    pub fn build_dataset<const N: usize>(&self) -> Result<BuildDataset<N>, DatasetError>{
+        let class2id = self.read_classes()?
             .into_iter()
             .enumerate()
             .map(|(i, class)|(class, i))
             .collect::<HashMap<_, _>>();
         let mut vocab_corpus = Vec::new();
-        let mut train_samples = self.train_iter()
+        let mut train_samples = Vec::new();
+        for sample in self.train_iter()?{
+            let sample = sample?;
+            vocab_corpus.push(sample.text.clone());
+            train_samples.push(sample);
+        }
+        let (vocab, embeddings) = self.resolve_vocab(&vocab_corpus)?;
+        if vocab.len() <= 2{
+            return Err(DatasetError::EmptyVocab);
+        }
+        let train_records = Self::samples_to_records::<N>(train_samples, &vocab, &class2id)?;
+        let mut dev_samples = Vec::new();
+        for sample in self.dev_iter()?{
+            dev_samples.push(sample?);
+        }
+        let dev_records = Self::samples_to_records::<N>(dev_samples, &vocab, &class2id)?;
+        let mut test_samples = Vec::new();
+        for sample in self.test_iter()?{
+            test_samples.push(sample?);
+        }
+        let test_records = Self::samples_to_records::<N>(test_samples, &vocab, &class2id)?;
+        Ok(BuildDataset{
+            train: train_records,
+            dev: dev_records,
+            test: test_records,
+            vocab,
+            embeddings
+        })
+    }
+    /// Resolves the vocabulary according to `config.vocab_type`: a cached
+    /// `Vocab` file is loaded as-is (and written back after a fresh build so
+    /// the next run skips corpus scanning entirely); an `Embedding` file is
+    /// parsed for both the token ids and their aligned pretrained vectors;
+    /// otherwise the vocabulary is built by scanning `vocab_corpus`.
+    fn resolve_vocab(&self, vocab_corpus: &[String]) -> ResolvedVocab{
+        match &self.config.vocab_type {
+            Vocabulary::Vocab(_) => {
+                let vocab_file = self.vocab_file.as_ref().expect("vocab file path missing");
+                if vocab_file.exists(){
+                    Ok((Self::load_vocab(vocab_file)?, None))
+                }else {
+                    let vocab = Self::build_vocab(
+                        vocab_corpus,
+                        &self.config.PAD,
+                        &self.config.UNK,
+                        self.config.min_freq,
+                        self.config.max_vocab_size
+                    );
+                    Self::save_vocab(vocab_file, &vocab)?;
+                    Ok((vocab, None))
+                }
+            }
+            Vocabulary::Embedding(_) => {
+                let vocab_file = self.vocab_file.as_ref().expect("vocab file path missing");
+                let (vocab, embeddings) = Self::load_embedding(vocab_file, &self.config.PAD, &self.config.UNK)?;
+                Ok((vocab, Some(embeddings)))
+            }
+            Vocabulary::Empty => {
+                let vocab = Self::build_vocab(
+                    vocab_corpus,
+                    &self.config.PAD,
+                    &self.config.UNK,
+                    self.config.min_freq,
+                    self.config.max_vocab_size
+                );
+                Ok((vocab, None))
+            }
+        }
+    }
+    /// Loads a prebuilt vocabulary serialized with `bincode`, the same way
+    /// precompiled dictionaries ship as binary blobs rather than being
+    /// rebuilt from source text on every run.
+    fn load_vocab(path: &Path) -> Result<BTreeMap<String, usize>, DatasetError>{
+        let file = File::open(path)?;
+        bincode::deserialize_from(BufReader::new(file))
+            .map_err(|e| DatasetError::Parse{ line: 0, path: path.to_path_buf(), message: format!("decode vocab file failed: {}", e) })
+    }
+    fn save_vocab(path: &Path, vocab: &BTreeMap<String, usize>) -> Result<(), DatasetError>{
+        let file = File::create(path)?;
+        bincode::serialize_into(file, vocab)
+            .map_err(|e| DatasetError::Parse{ line: 0, path: path.to_path_buf(), message: format!("encode vocab file failed: {}", e) })
+    }
+    /// Parses a word2vec/GloVe-style text embedding file: an optional
+    /// `<num_words> <dim>` header followed by one `token v1 v2 ... vdim`
+    /// line per token. Ids are assigned in file order with `pad`/`unk`
+    /// reserved first, so the returned matrix rows line up with the vocab.
+    /// A token already in the vocab (a repeat, or `pad`/`unk`) keeps its
+    /// first id and its later lines are skipped.
+    fn load_embedding(path: &Path, pad: &str, unk: &str) -> LoadedEmbedding{
+        let file = File::open(path)?;
+        let mut lines = BufReader::new(file).lines();
+        let first_line = match lines.next(){
+            None => return Err(DatasetError::Parse{ line: 0, path: path.to_path_buf(), message: "empty embedding file".to_string() }),
+            Some(line) => line?
+        };
+        let header = first_line.split_whitespace().collect::<Vec<_>>();
+        let is_header = header.len() == 2
+            && header[0].parse::<usize>().is_ok()
+            && header[1].parse::<usize>().is_ok();
+        let data_lines: Box<dyn Iterator<Item = std::io::Result<String>>> = if is_header{
+            Box::new(lines)
+        }else {
+            Box::new(std::iter::once(Ok(first_line)).chain(lines))
+        };
+        let mut entries = Vec::new();
+        for (i, line) in data_lines.enumerate(){
+            let line = line?;
+            let mut parts = line.split_whitespace();
+            let token = parts.next()
+                .ok_or_else(|| DatasetError::MissingField{ line: i + 1, path: path.to_path_buf() })?
+                .to_string();
+            let vector = parts
+                .map(|v| v.parse::<f32>().map_err(|e| DatasetError::Parse{
+                    line: i + 1,
+                    path: path.to_path_buf(),
+                    message: format!("invalid embedding value {:?}: {}", v, e)
+                }))
+                .collect::<Result<Vec<_>, _>>()?;
+            entries.push((token, vector));
+        }
+        let dim = entries.first().map(|(_, v)|v.len()).unwrap_or(0);
+        let mut vocab = BTreeMap::new();
+        vocab.insert(pad.to_string(), 0);
+        vocab.insert(unk.to_string(), 1);
+        let mut embeddings = vec![vec![0f32; dim]; 2];
+        for (token, vector) in entries{
+            if vocab.contains_key(&token){
+                continue;
+            }
+            let id = vocab.len();
+            vocab.insert(token, id);
+            embeddings.push(vector);
+        }
+        Ok((vocab, embeddings))
+    }
+    /// Builds a frequency-ranked vocabulary over `corpus`: tokens seen fewer
+    /// than `min_freq` times are discarded, ties break lexicographically for
+    /// deterministic ids, and id 0 / id 1 are always reserved for `pad` /
+    /// `unk` so they're stable regardless of the corpus contents.
+    fn build_vocab(
+        corpus: &[String],
+        pad: &str,
+        unk: &str,
+        min_freq: usize,
+        max_vocab_size: Option<usize>) -> BTreeMap<String, usize>{
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for text in corpus{
+            for ch in text.chars(){
+                *counts.entry(ch.to_string()).or_insert(0) += 1;
+            }
+        }
+        Self::vocab_from_counts(counts, pad, unk, min_freq, max_vocab_size)
+    }
+    /// Assigns contiguous, frequency-ranked ids to already-counted tokens.
+    /// Shared by the eager corpus scan and the streaming vocab pass so
+    /// neither has to retain sample text just to get here.
+    fn vocab_from_counts(
+        counts: HashMap<String, usize>,
+        pad: &str,
+        unk: &str,
+        min_freq: usize,
+        max_vocab_size: Option<usize>) -> BTreeMap<String, usize>{
+        let mut tokens = counts.into_iter().collect::<Vec<_>>();
+        tokens.sort_by(|(a_token, a_count), (b_token, b_count)|{
+            b_count.cmp(a_count).then_with(||a_token.cmp(b_token))
+        });
+        let mut vocab = BTreeMap::new();
+        vocab.insert(pad.to_string(), 0);
+        vocab.insert(unk.to_string(), 1);
+        let mut next_id = 2;
+        for (token, count) in tokens{
+            if count < min_freq{
+                continue;
+            }
+            if let Some(max_vocab_size) = max_vocab_size{
+                if next_id - 2 >= max_vocab_size{
+                    break;
+                }
+            }
+            vocab.insert(token, next_id);
+            next_id += 1;
+        }
+        vocab
+    }
+    /// Pass one of the streaming API: reads the training file once, counting
+    /// token occurrences without retaining any sample text, then assigns ids
+    /// the same way the eager corpus scan does.
+    fn build_vocab_streaming(&self) -> Result<BTreeMap<String, usize>, DatasetError>{
+        let mut counts: HashMap<String, usize> = HashMap::new();
+        for sample in self.train_iter()?{
+            let sample = sample?;
+            for ch in sample.text.chars(){
+                *counts.entry(ch.to_string()).or_insert(0) += 1;
+            }
+        }
+        Ok(Self::vocab_from_counts(counts, &self.config.PAD, &self.config.UNK, self.config.min_freq, self.config.max_vocab_size))
+    }
+    /// Streaming counterpart of [`Self::resolve_vocab`]: never materializes
+    /// the training text, at the cost of not surfacing an embedding matrix
+    /// (callers needing pretrained vectors should use [`Self::build_dataset`]).
+    fn resolve_vocab_streaming(&self) -> Result<BTreeMap<String, usize>, DatasetError>{
+        match &self.config.vocab_type {
+            Vocabulary::Vocab(_) => {
+                let vocab_file = self.vocab_file.as_ref().expect("vocab file path missing");
+                if vocab_file.exists(){
+                    Self::load_vocab(vocab_file)
+                }else {
+                    let vocab = self.build_vocab_streaming()?;
+                    Self::save_vocab(vocab_file, &vocab)?;
+                    Ok(vocab)
+                }
+            }
+            Vocabulary::Embedding(_) => {
+                let vocab_file = self.vocab_file.as_ref().expect("vocab file path missing");
+                let (vocab, _embeddings) = Self::load_embedding(vocab_file, &self.config.PAD, &self.config.UNK)?;
+                Ok(vocab)
+            }
+            Vocabulary::Empty => self.build_vocab_streaming()
+        }
+    }
+    /// Streaming counterpart of [`Self::build_dataset`]: pass one builds the
+    /// vocabulary without retaining any sample text, pass two returns three
+    /// [`RecordStream`]s that tokenize and yield one record at a time, so
+    /// peak memory stays constant regardless of corpus size.
+    pub fn build_dataset_streaming<const N: usize>(&self) -> Result<(RecordStream<N>, RecordStream<N>, RecordStream<N>, BTreeMap<String, usize>), DatasetError>{
+        let class2id = Rc::new(self.read_classes()?
             .into_iter()
-            .map(|s|{
-                vocab_corpus.push(s.text.clone());
-                s
-            })
-            .collect::<Vec<_>>();
-        let vocab = vocab_corpus
-            .iter()
-            .map(|text|text.chars())
-            .flatten()
             .enumerate()
-            .map(|(i, ch)|(ch.to_string(), i+1))
-            .collect::<BTreeMap<_, _>>();
-        let train_records = Self::samples_to_records::<N>(train_samples, &vocab, &class2id);
-        let mut dev_samples = self.dev_iter().into_iter().collect::<Vec<_>>();
-        let dev_records = Self::samples_to_records::<N>(dev_samples, &vocab, &class2id);
-        let mut test_samples = self.test_iter().into_iter().collect::<Vec<_>>();
-        let test_records = Self::samples_to_records::<N>(test_samples, &vocab, &class2id);
-        (train_records, dev_records, test_records, vocab)
+            .map(|(i, class)|(class, i))
+            .collect::<HashMap<_, _>>());
+        let vocab = Rc::new(self.resolve_vocab_streaming()?);
+        if vocab.len() <= 2{
+            return Err(DatasetError::EmptyVocab);
+        }
+        let train_stream = RecordStream::new(self.train_iter()?, vocab.clone(), class2id.clone());
+        let dev_stream = RecordStream::new(self.dev_iter()?, vocab.clone(), class2id.clone());
+        let test_stream = RecordStream::new(self.test_iter()?, vocab.clone(), class2id.clone());
+        Ok((train_stream, dev_stream, test_stream, (*vocab).clone()))
     }
     fn samples_to_records<const N: usize>(
         samples: Vec<ClassifierSample>,
         vocab: &BTreeMap<String, usize>,
-        class2id: & HashMap<String, usize>) -> Vec<ClassifierRecord<N>>{
+        class2id: & HashMap<String, usize>) -> Result<Vec<ClassifierRecord<N>>, DatasetError>{
         samples
             .into_iter()
-            .map(|s|(
-                s.text.chars()
-                    .into_iter()
-                    .map(|c|*vocab.get(&c.to_string()).unwrap_or(&0))
-                    .collect(),
-                s.label
+            .map(|s|{
+                let word_ids = s.text.chars()
+                    .map(|c|*vocab.get(&c.to_string()).unwrap_or(&1))
+                    .collect();
+                let label_id = s.label
                     .parse::<usize>()
-                    .map_err(|e|{
-                        eprintln!("num {:?} parse error {}", s.label, e);
-                        e
-                    })
-                    // .unwrap_or(
-                    //     *class2id.get(&s.label)
-                    //         .expect(&format!("invalid label {}", &s.label))
-                    // )
-                    .unwrap_or_else(|e|{
-                        *class2id.get(&s.label)
-                            .expect(&format!("invalid label {}, error {}", &s.label, e))
-                    }
-                    )
-            )
-            )
-            .map(|(word_ids, label_id)|ClassifierRecord::new(word_ids, label_id))
+                    .ok()
+                    .or_else(||class2id.get(&s.label).copied())
+                    .ok_or_else(||DatasetError::BadLabel{ value: s.label.clone(), line: s.line })?;
+                Ok(ClassifierRecord::new(word_ids, label_id))
+            })
             .collect()
     }
 }
 
 struct ClassifierIter{
-    reader: BufReader<File>
+    reader: Box<dyn BufRead>,
+    normalization: Option<NormForm>,
+    delimiter: Delimiter,
+    path: PathBuf,
+    line_no: usize
 }
 
 impl Iterator for ClassifierIter{
-    type Item = ClassifierSample;
+    type Item = Result<ClassifierSample, DatasetError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut line = String::new();
         match self.reader.read_line(&mut line){
             Ok(len) => {
                 if len > 0 {
-                    Some(ClassifierSample::from(line.trim_end()))
+                    self.line_no += 1;
+                    let line = line.trim_end();
+                    let result = parse_line::<ClassifierSample>(line, self.delimiter)
+                        .map(|mut sample|{
+                            sample.text = normalize_text(&sample.text, self.normalization);
+                            sample.line = self.line_no;
+                            sample
+                        })
+                        .map_err(|e| parse_error_to_dataset(e, self.line_no, &self.path));
+                    Some(result)
                 }else {
                     None
                 }
             },
-            Err(e) => {
-                eprintln!("{}", e);
-                None
-            }
+            Err(e) => Some(Err(DatasetError::Io(e)))
         }
     }
 }
 
 impl ClassifierIter{
-    fn new(reader: BufReader<File>) ->Self{
+    fn new(reader: Box<dyn BufRead>, path: PathBuf, normalization: Option<NormForm>, delimiter: Delimiter) ->Self{
         Self{
-            reader
+            reader,
+            normalization,
+            delimiter,
+            path,
+            line_no: 0
         }
     }
 }
 #[derive(Debug)]
+#[allow(dead_code)]
 pub struct ClassifierRecord<const N: usize>{
     word_ids: [usize; N],
     label_id: usize
@@ -221,43 +680,101 @@ impl<const N: usize> ClassifierRecord<N> {
     }
 }
 
+/// Lazily tokenizes one [`ClassifierSample`] at a time against a
+/// pre-resolved vocabulary, never materializing the full file as `Vec`s.
+/// Returned by [`ClassifierDataset::build_dataset_streaming`]; the vocab and
+/// class map are shared across the train/dev/test streams via `Rc` rather
+/// than cloned.
+pub struct RecordStream<const N: usize>{
+    iter: ClassifierIter,
+    vocab: Rc<BTreeMap<String, usize>>,
+    class2id: Rc<HashMap<String, usize>>
+}
+
+impl<const N: usize> RecordStream<N>{
+    fn new(iter: ClassifierIter, vocab: Rc<BTreeMap<String, usize>>, class2id: Rc<HashMap<String, usize>>) -> Self{
+        Self{
+            iter,
+            vocab,
+            class2id
+        }
+    }
+}
+
+impl<const N: usize> Iterator for RecordStream<N>{
+    type Item = Result<ClassifierRecord<N>, DatasetError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let sample = match self.iter.next()?{
+            Ok(sample) => sample,
+            Err(e) => return Some(Err(e))
+        };
+        let word_ids = sample.text.chars()
+            .map(|c| *self.vocab.get(&c.to_string()).unwrap_or(&1))
+            .collect::<Vec<_>>();
+        let label_id = match sample.label.parse::<usize>().ok()
+            .or_else(|| self.class2id.get(&sample.label).copied()){
+            Some(id) => id,
+            None => return Some(Err(DatasetError::BadLabel{ value: sample.label, line: self.iter.line_no }))
+        };
+        Some(Ok(ClassifierRecord::new(word_ids, label_id)))
+    }
+}
+
 #[derive(Debug)]
 struct  ClassifierSample{
     text: String,
     label: String,
+    line: usize,
 }
 impl ClassifierSample{
     fn new(text: String, label: String) ->Self{
         Self{
             text,
-            label
+            label,
+            line: 0,
         }
     }
 }
 
-impl From<String> for ClassifierSample{
-    fn from(content: String) -> Self {
-        content.split_once('\t')
-            .map(|(front, back)|{
-                ClassifierSample::new(front.to_string(), back.to_string())
-            }).expect("invalid classifier line")
+impl RecordParser for ClassifierSample{
+    fn fields_expected() -> usize { 2 }
+
+    fn parse_fields(fields: &[&str]) -> Result<Self, ParseError> {
+        if fields.len() != Self::fields_expected(){
+            return Err(ParseError::FieldCount{ expected: Self::fields_expected(), found: fields.len() });
+        }
+        Ok(ClassifierSample::new(fields[0].to_string(), fields[1].to_string()))
     }
-}
 
-impl From<& str> for ClassifierSample {
-    fn from(content: &str) -> Self {
-        content.split_once('\t')
-            .map(|(front, back)|{
-                ClassifierSample::new(front.to_string(), back.to_string())
-            }).expect("invalid classifier line")
+    #[cfg(feature = "json-lines")]
+    fn parse_json(line: &str) -> Result<Self, ParseError> {
+        let value: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| ParseError::Json(e.to_string()))?;
+        let text = value.get("text")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ParseError::Json("missing \"text\" field".to_string()))?;
+        let label = value.get("label")
+            .ok_or_else(|| ParseError::Json("missing \"label\" field".to_string()))?;
+        let label = match label {
+            serde_json::Value::String(s) => s.clone(),
+            other => other.to_string()
+        };
+        Ok(ClassifierSample::new(text.to_string(), label))
     }
 }
 // tagging
+//
+// Sequence-tagging support is parsed but not yet wired to a `build_dataset`
+// entry point the way `ClassifierDataset` is, so these types are unused for
+// now; `#[allow(dead_code)]` keeps that from failing the lint gate.
+#[allow(dead_code)]
 struct TaggingDataset<'a>{
     path: & 'a Path,
     config: DataConfig
 }
 
+#[allow(dead_code)]
 impl<'a> TaggingDataset<'a> {
     pub fn new(path: & 'a Path) -> Self {
         Self{
@@ -272,22 +789,33 @@ impl<'a> TaggingDataset<'a> {
         }
     }
 }
+#[allow(dead_code)]
 struct TaggingIter{
-    reader: BufReader<File>
+    reader: Box<dyn BufRead>,
+    normalization: Option<NormForm>,
+    delimiter: Delimiter,
+    path: PathBuf,
+    line_no: usize
 }
+#[allow(dead_code)]
 impl TaggingIter{
-    fn new(reader: BufReader<File>)->Self{
+    fn new(reader: Box<dyn BufRead>, path: PathBuf, normalization: Option<NormForm>, delimiter: Delimiter)->Self{
         Self{
-            reader
+            reader,
+            normalization,
+            delimiter,
+            path,
+            line_no: 0
         }
     }
 }
 
 impl Iterator for TaggingIter{
-    type Item = TaggingSample;
+    type Item = Result<TaggingSample, DatasetError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut lines = Vec::new();
+        let mut start_line = self.line_no + 1;
         loop {
             let mut line = String::new();
             match self.reader.read_line(&mut line){
@@ -295,47 +823,79 @@ impl Iterator for TaggingIter{
                     if len == 0{
                         break
                     }
+                    self.line_no += 1;
+                    if line.trim_end().is_empty(){
+                        if lines.is_empty(){
+                            start_line = self.line_no + 1;
+                            continue;
+                        }
+                        break;
+                    }
                     lines.push(line);
                 }
-                Err(e) => {
-                    eprintln!("{}", e);
-                    return None
-                }
+                Err(e) => return Some(Err(DatasetError::Io(e)))
             }
         }
-        Some(TaggingSample::from(lines))
+        if lines.is_empty(){
+            return None;
+        }
+        let result = TaggingSample::from_lines(lines, self.delimiter, start_line, &self.path)
+            .map(|mut sample|{
+                for (token, _) in sample.items.iter_mut(){
+                    *token = normalize_text(token, self.normalization);
+                }
+                sample
+            });
+        Some(result)
     }
 }
+#[allow(dead_code)]
 struct TaggingSample{
     items: Vec<(String, String)>
 }
 
+#[allow(dead_code)]
 impl TaggingSample{
     fn new(items: Vec<(String, String)>) ->Self{
         Self{
             items
         }
     }
-}
 
-impl From<Vec<String>> for TaggingSample {
-    fn from(contents: Vec<String>) -> Self {
+    fn from_lines(contents: Vec<String>, delimiter: Delimiter, start_line: usize, path: &Path) -> Result<Self, DatasetError>{
         let items = contents.into_iter()
-            .map(|content|{
-                content.trim_end().split_once('\t')
-                    .map(|(front, back)|(front.to_string(), back.to_string()))
-                    .expect("invalid tagging line")
-            }).collect::<Vec<_>>();
-        TaggingSample::new(items)
+            .enumerate()
+            .map(|(i, content)|{
+                let line = content.trim_end();
+                parse_line::<(String, String)>(line, delimiter)
+                    .map_err(|e| parse_error_to_dataset(e, start_line + i, path))
+            }).collect::<Result<Vec<_>, _>>()?;
+        Ok(TaggingSample::new(items))
+    }
+}
+
+impl RecordParser for (String, String){
+    fn fields_expected() -> usize { 2 }
+
+    fn parse_fields(fields: &[&str]) -> Result<Self, ParseError> {
+        if fields.len() != Self::fields_expected(){
+            return Err(ParseError::FieldCount{ expected: Self::fields_expected(), found: fields.len() });
+        }
+        Ok((fields[0].to_string(), fields[1].to_string()))
     }
 }
 
 // similarity
+//
+// Same situation as `TaggingDataset` above: parsing support exists, but
+// there's no `build_dataset` entry point wired up yet.
+#[allow(dead_code)]
 struct SimilarityDataset<'a>{
     path: & 'a Path,
     config: DataConfig
 }
 
+#[allow(dead_code)]
 impl <'a> SimilarityDataset<'a> {
     pub fn new(path: & 'a Path) -> Self {
         Self{
@@ -350,43 +910,63 @@ impl <'a> SimilarityDataset<'a> {
         }
     }
 }
+#[allow(dead_code)]
 struct SimilarityIter{
-    reader: BufReader<File>
+    reader: Box<dyn BufRead>,
+    normalization: Option<NormForm>,
+    delimiter: Delimiter,
+    path: PathBuf,
+    line_no: usize
 }
 
+#[allow(dead_code)]
 impl SimilarityIter {
-    pub fn new(reader: BufReader<File>) ->Self{
+    pub fn new(reader: Box<dyn BufRead>, path: PathBuf, normalization: Option<NormForm>, delimiter: Delimiter) ->Self{
         Self{
-            reader
+            reader,
+            normalization,
+            delimiter,
+            path,
+            line_no: 0
         }
     }
 }
 
 impl Iterator for SimilarityIter{
-    type Item = SimilaritySample;
+    type Item = Result<SimilaritySample, DatasetError>;
 
     fn next(&mut self) -> Option<Self::Item> {
         let mut line = String::new();
         match self.reader.read_line(&mut line){
             Ok(len) => {
                 if len > 0{
-                    return Some(SimilaritySample::from(line))
+                    self.line_no += 1;
+                    let line = line.trim_end();
+                    let result = parse_line::<SimilaritySample>(line, self.delimiter)
+                        .map(|mut sample|{
+                            sample.text_a = normalize_text(&sample.text_a, self.normalization);
+                            sample.text_b = normalize_text(&sample.text_b, self.normalization);
+                            sample
+                        })
+                        .map_err(|e| parse_error_to_dataset(e, self.line_no, &self.path));
+                    return Some(result)
                 }
             }
             Err(e) => {
-                eprintln!("{}", e);
-                return None
+                return Some(Err(DatasetError::Io(e)))
             }
         }
         None
     }
 }
+#[allow(dead_code)]
 struct SimilaritySample{
     text_a: String,
     text_b: String,
     similar: bool
 }
 
+#[allow(dead_code)]
 impl SimilaritySample{
     fn new(text_a: String, text_b: String, similar: bool) ->Self{
         Self{
@@ -397,15 +977,127 @@ impl SimilaritySample{
     }
 }
 
-impl From<String> for SimilaritySample{
-    fn from(content: String) -> Self {
-        let mut sp = content.splitn(3, |c| c == '\t');
-        let text_a = sp.next().expect("invalid similarity sample");
-        let text_b = sp.next().expect("invalid similarity sample");
-        let similar = sp.next()
-            .expect("invalid similarity sample")
-            .parse::<u8>()
-            .expect("invalid similarity tag") != 0;
-        SimilaritySample::new(text_a.to_string(), text_b.to_string(), similar)
+impl RecordParser for SimilaritySample{
+    fn fields_expected() -> usize { 3 }
+
+    fn parse_fields(fields: &[&str]) -> Result<Self, ParseError> {
+        if fields.len() != Self::fields_expected(){
+            return Err(ParseError::FieldCount{ expected: Self::fields_expected(), found: fields.len() });
+        }
+        let similar = fields[2].parse::<u8>()
+            .map_err(|e| ParseError::InvalidField(format!("invalid similarity tag {:?}: {}", fields[2], e)))? != 0;
+        Ok(SimilaritySample::new(fields[0].to_string(), fields[1].to_string(), similar))
+    }
+
+    #[cfg(feature = "json-lines")]
+    fn parse_json(line: &str) -> Result<Self, ParseError> {
+        let value: serde_json::Value = serde_json::from_str(line)
+            .map_err(|e| ParseError::Json(e.to_string()))?;
+        let a = value.get("a")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ParseError::Json("missing \"a\" field".to_string()))?;
+        let b = value.get("b")
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| ParseError::Json("missing \"b\" field".to_string()))?;
+        let label = value.get("label")
+            .and_then(|v| v.as_u64())
+            .ok_or_else(|| ParseError::Json("missing \"label\" field".to_string()))?;
+        Ok(SimilaritySample::new(a.to_string(), b.to_string(), label != 0))
+    }
+}
+
+#[cfg(test)]
+mod tests{
+    use super::*;
+    use std::io::{Cursor, Read};
+
+    #[test]
+    fn vocab_from_counts_breaks_ties_lexicographically(){
+        let mut counts = HashMap::new();
+        counts.insert("b".to_string(), 2);
+        counts.insert("a".to_string(), 2);
+        counts.insert("c".to_string(), 1);
+        let vocab = ClassifierDataset::vocab_from_counts(counts, "<PAD>", "<UNK>", 1, None);
+        assert_eq!(vocab["<PAD>"], 0);
+        assert_eq!(vocab["<UNK>"], 1);
+        assert_eq!(vocab["a"], 2);
+        assert_eq!(vocab["b"], 3);
+        assert_eq!(vocab["c"], 4);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn vocab_from_counts_applies_min_freq_and_max_size(){
+        let mut counts = HashMap::new();
+        counts.insert("a".to_string(), 5);
+        counts.insert("b".to_string(), 1);
+        counts.insert("c".to_string(), 5);
+        let vocab = ClassifierDataset::vocab_from_counts(counts, "<PAD>", "<UNK>", 2, Some(1));
+        assert_eq!(vocab.len(), 3);
+        assert!(!vocab.contains_key("b"));
+    }
+
+    #[test]
+    fn open_reader_transparently_decompresses_gzip(){
+        use flate2::write::GzEncoder;
+        use flate2::Compression;
+        use std::io::Write;
+
+        let dir = std::env::temp_dir();
+        let plain_path = dir.join(format!("nl_readers_test_plain_{}.txt", std::process::id()));
+        let gz_path = dir.join(format!("nl_readers_test_{}.txt.gz", std::process::id()));
+
+        std::fs::write(&plain_path, b"hello\tworld\n").unwrap();
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(b"hello\tworld\n").unwrap();
+        let compressed = encoder.finish().unwrap();
+        std::fs::write(&gz_path, compressed).unwrap();
+
+        let mut plain_content = String::new();
+        open_reader(&plain_path).unwrap().read_to_string(&mut plain_content).unwrap();
+        assert_eq!(plain_content, "hello\tworld\n");
+
+        let mut gz_content = String::new();
+        open_reader(&gz_path).unwrap().read_to_string(&mut gz_content).unwrap();
+        assert_eq!(gz_content, "hello\tworld\n");
+
+        std::fs::remove_file(&plain_path).ok();
+        std::fs::remove_file(&gz_path).ok();
+    }
+
+    #[test]
+    fn parse_line_keeps_extra_delimiters_in_last_field(){
+        let sample: ClassifierSample = parse_line("a\tb\tc\td", Delimiter::Tab).unwrap();
+        assert_eq!(sample.text, "a");
+        assert_eq!(sample.label, "b\tc\td");
+    }
+
+    #[test]
+    fn tagging_iter_splits_on_blank_lines_and_terminates(){
+        let data = "w1\tO\nw2\tB\n\nw3\tO\n";
+        let reader: Box<dyn BufRead> = Box::new(BufReader::new(Cursor::new(data.as_bytes())));
+        let mut iter = TaggingIter::new(reader, PathBuf::from("<test>"), None, Delimiter::Tab);
+
+        let first = iter.next().unwrap().unwrap();
+        assert_eq!(first.items, vec![("w1".to_string(), "O".to_string()), ("w2".to_string(), "B".to_string())]);
+
+        let second = iter.next().unwrap().unwrap();
+        assert_eq!(second.items, vec![("w3".to_string(), "O".to_string())]);
+
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn samples_to_records_reports_source_line_on_bad_label(){
+        let samples = vec![
+            ClassifierSample{ text: "ok".to_string(), label: "0".to_string(), line: 1 },
+            ClassifierSample{ text: "bad".to_string(), label: "nope".to_string(), line: 2 },
+        ];
+        let vocab = BTreeMap::new();
+        let class2id = HashMap::new();
+        let err = ClassifierDataset::samples_to_records::<8>(samples, &vocab, &class2id).unwrap_err();
+        match err{
+            DatasetError::BadLabel{ line, .. } => assert_eq!(line, 2),
+            other => panic!("expected BadLabel, got {:?}", other)
+        }
+    }
+}
@@ -1,18 +1,20 @@
-mod dataset;
 use std::path::Path;
-use dataset::{DataConfig, ClassifierDataset};
+use nl_readers::dataset::{ClassifierDataset, DatasetError};
 
-fn build_classifier(){
+fn build_classifier() -> Result<(), DatasetError>{
     let path = Path::new("data/classifier/THUCNews");
-    let dataset = ClassifierDataset::new(&path);
-    let (train, dev, test, vocab) = dataset.build_dataset::<32usize>();
-    println!("train {} samples", train.len());
-    println!("dev {} samples", dev.len());
-    println!("test {} samples", test.len());
-    println!("vocab size: {}", vocab.len());
+    let dataset = ClassifierDataset::new(path);
+    let built = dataset.build_dataset::<32usize>()?;
+    println!("train {} samples", built.train.len());
+    println!("dev {} samples", built.dev.len());
+    println!("test {} samples", built.test.len());
+    println!("vocab size: {}", built.vocab.len());
+    Ok(())
 }
 
 fn main() {
-    build_classifier();
+    if let Err(e) = build_classifier(){
+        eprintln!("failed to build classifier dataset: {}", e);
+    }
     println!("Hello, world!");
 }